@@ -0,0 +1,89 @@
+//! Load test of the concurrent hits on a single locked store and on the sharded ones.
+//!
+//! Run with `cargo bench --bench contention`, on a machine with several cores (the contention
+//! on the lock of the single store only shows when the requests run in parallel). The number of
+//! the worker threads defaults to the available parallelism, and can be set with the `WORKERS`
+//! environment variable.
+
+use std::{
+    env,
+    sync::Mutex,
+    thread::available_parallelism,
+    time::{Duration, Instant},
+};
+
+use axum_08::{
+    body::Body,
+    http::{Method, Request, Uri},
+    routing::get,
+    Router,
+};
+use axum_response_cache::{AsyncCache, AsyncCacheLayer, CachedResponse, ShardedCache};
+use cached::TimedCache;
+use tokio::runtime::Builder;
+use tower::ServiceExt;
+
+const KEYS: usize = 256;
+const TASKS_PER_WORKER: usize = 8;
+const REQUESTS_PER_TASK: usize = 20_000;
+
+fn store() -> TimedCache<(Method, Uri), CachedResponse> {
+    TimedCache::with_lifespan(Duration::from_secs(600))
+}
+
+/// Send the requests for all the keys from many tasks at once, returning the requests per second.
+async fn load<C>(store: C, tasks: usize) -> f64
+where
+    C: AsyncCache<(Method, Uri)> + Send + Sync + 'static,
+{
+    let router = Router::new()
+        .fallback(get(|| async { "contention" }))
+        .layer(AsyncCacheLayer::with(store));
+    // every response is cached before the measure, so that only the hits are measured
+    for key in 0..KEYS {
+        let request = Request::get(format!("/{key}")).body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap();
+    }
+
+    let started = Instant::now();
+    let handles: Vec<_> = (0..tasks)
+        .map(|task| {
+            let router = router.clone();
+            tokio::spawn(async move {
+                for request in 0..REQUESTS_PER_TASK {
+                    let key = (task * 31 + request) % KEYS;
+                    let request = Request::get(format!("/{key}")).body(Body::empty()).unwrap();
+                    router.clone().oneshot(request).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    (tasks * REQUESTS_PER_TASK) as f64 / started.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let workers = env::var("WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .unwrap_or_else(|| available_parallelism().map_or(1, |workers| workers.get()));
+    let tasks = workers * TASKS_PER_WORKER;
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(workers)
+        .enable_all()
+        .build()
+        .unwrap();
+    println!("{workers} workers, {tasks} tasks, {KEYS} keys");
+
+    let single = runtime.block_on(load(Mutex::new(store()), tasks));
+    println!("single store: {single:>12.0} req/s");
+    for shards in [4, 16, 64] {
+        let sharded = runtime.block_on(load(ShardedCache::new(shards, store), tasks));
+        println!(
+            "{shards:>2} shards:    {sharded:>12.0} req/s ({:+.1}%)",
+            (sharded / single - 1.0) * 100.0
+        );
+    }
+}
@@ -0,0 +1,958 @@
+//! Caching responses in asynchronous stores, see [`AsyncCacheLayer`].
+
+use std::{
+    convert::Infallible,
+    error::Error,
+    fmt::{self, Debug, Display},
+    future::{ready, Future},
+    hash::Hash,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use cached::Cached;
+use tower::{Layer, Service};
+use tracing::{debug, instrument, warn};
+use tracing_futures::Instrument as _;
+
+use crate::axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{header, response::Parts, HeaderName, Method, Request, StatusCode},
+    response::{IntoResponse as _, Response},
+    BoxError,
+};
+use crate::{
+    cacheable_response, call_ready, default_etag, inner_span, lock, with_key_header, AgeHeader,
+    BasicKeyer, CacheConfig, CacheCounters, CacheStats, CacheStatus, CacheableStatus,
+    CachedResponse, Clock, Compression, Config, Hook, KeyCallback, Keyer,
+};
+
+/// The trait for asynchronous cache stores, eg. the ones accessed over a network connection.
+///
+/// Unlike the stores of [`CacheLayer`](crate::CacheLayer), these aren’t locked by the layer, so
+/// they have to synchronize the access on their own. They’re also responsible for expiring their
+/// entries.
+pub trait AsyncCache<K> {
+    /// The error of the store’s operations.
+    type Error: Display;
+
+    /// Get the response cached for the key.
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send;
+
+    /// Cache the response for the key, replacing the previous one.
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Remove the response cached for the key.
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// Any of the synchronous stores locked behind a mutex can be used as an asynchronous one (though
+/// it blocks while accessing the store).
+impl<K, C> AsyncCache<K> for Mutex<C>
+where
+    C: Cached<K, CachedResponse> + Send,
+    K: Hash + Eq + Sync,
+{
+    type Error = Infallible;
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send {
+        ready(Ok(lock(self).cache_get(key).cloned()))
+    }
+
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        lock(self).cache_set(key, value);
+        ready(Ok(()))
+    }
+
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        lock(self).cache_remove(key);
+        ready(Ok(()))
+    }
+}
+
+/// The stores shared by several layers, eg. with their keys in different namespaces (see
+/// [`Keyer::namespace`]).
+impl<K, C> AsyncCache<K> for Arc<C>
+where
+    C: AsyncCache<K> + Send + Sync,
+    K: Sync,
+{
+    type Error = C::Error;
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send {
+        C::get(self, key)
+    }
+
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        C::set(self, key, value)
+    }
+
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        C::remove(self, key)
+    }
+}
+
+/// The failure of an [`AsyncCache`], along with the operation that failed.
+#[derive(Debug)]
+pub enum CacheError<E> {
+    /// Reading a response from the store failed.
+    Read(E),
+    /// Storing a response failed.
+    Write(E),
+    /// Removing a response from the store failed.
+    Remove(E),
+}
+
+impl<E> CacheError<E> {
+    /// The error of the store.
+    pub fn into_inner(self) -> E {
+        match self {
+            Self::Read(err) | Self::Write(err) | Self::Remove(err) => err,
+        }
+    }
+}
+
+impl<E: Display> Display for CacheError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(err) => write!(f, "failed to read the cache: {err}"),
+            Self::Write(err) => write!(f, "failed to update the cache: {err}"),
+            Self::Remove(err) => write!(f, "failed to invalidate the cache: {err}"),
+        }
+    }
+}
+
+impl<E: Debug + Display> Error for CacheError<E> {}
+
+/// How an [`AsyncCacheLayer`] handles the failures of its store, see
+/// [`AsyncCacheLayer::on_store_error`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoreErrorPolicy {
+    /// Log the failures, and pass the requests to the wrapped service as if their responses
+    /// weren’t cached.
+    #[default]
+    Degrade,
+    /// Log the failures, and respond with `503 Service Unavailable` without reaching the wrapped
+    /// service.
+    Fail,
+}
+
+/// The layer caching the responses of the wrapped service in an [`AsyncCache`]. It is generic
+/// over the store used (`C`) and a `Keyer` (`K`) used to obtain the key for cached responses.
+///
+/// It supports a subset of the settings of [`CacheLayer`](crate::CacheLayer). By default, the
+/// failures of the store are logged and otherwise ignored: the requests are passed to the wrapped
+/// service as if their responses weren’t cached (see [`AsyncCacheLayer::on_store_error`]).
+pub struct AsyncCacheLayer<C, K: Keyer> {
+    cache: Arc<C>,
+    config: Config,
+    on_store_error: StoreErrorPolicy,
+    keyer: Arc<K>,
+    counters: Arc<CacheCounters>,
+    on_hit: Option<KeyCallback<K::Key>>,
+    on_miss: Option<KeyCallback<K::Key>>,
+}
+
+impl<C, K: Keyer> Clone for AsyncCacheLayer<C, K> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: Arc::clone(&self.cache),
+            config: self.config.clone(),
+            on_store_error: self.on_store_error,
+            keyer: Arc::clone(&self.keyer),
+            counters: Arc::clone(&self.counters),
+            on_hit: self.on_hit.clone(),
+            on_miss: self.on_miss.clone(),
+        }
+    }
+}
+
+impl<C, K> AsyncCacheLayer<C, K>
+where
+    C: AsyncCache<K::Key>,
+    K: Keyer,
+    K::Key: Debug,
+{
+    /// Create a new cache layer with a given store and the default body size limit of 128 MB.
+    pub fn with_cache_and_keyer(cache: C, keyer: K) -> Self {
+        Self {
+            cache: Arc::new(cache),
+            config: Config::default(),
+            on_store_error: StoreErrorPolicy::Degrade,
+            keyer: Arc::new(keyer),
+            counters: Arc::default(),
+            on_hit: None,
+            on_miss: None,
+        }
+    }
+
+    /// Create a new cache layer with a given store, keyer and settings, see
+    /// [`CacheLayer::from_config`](crate::CacheLayer::from_config). The settings not supported by
+    /// this layer (like `use_stale_on_failure` or `coalesce_requests`) are ignored.
+    pub fn from_config(cache: C, keyer: K, config: CacheConfig) -> Self {
+        Self {
+            config: config.into(),
+            ..Self::with_cache_and_keyer(cache, keyer)
+        }
+    }
+
+    /// Change the maximum body size limit, see
+    /// [`CacheLayer::body_limit`](crate::CacheLayer::body_limit).
+    pub fn body_limit(mut self, new_limit: usize) -> Self {
+        self.config.limit = new_limit;
+        self
+    }
+
+    /// Store only the responses with bodies of at least the given size, see
+    /// [`CacheLayer::min_body_size`](crate::CacheLayer::min_body_size).
+    pub fn min_body_size(mut self, size: usize) -> Self {
+        self.config.min_body_size = size;
+        self
+    }
+
+    /// Pass the responses with empty bodies through uncached, see
+    /// [`CacheLayer::skip_empty_bodies`](crate::CacheLayer::skip_empty_bodies).
+    pub fn skip_empty_bodies(mut self) -> Self {
+        self.config.skip_empty_bodies = true;
+        self
+    }
+
+    /// Share a single copy of the identical bodies between all the entries storing them, see
+    /// [`CacheLayer::dedup_bodies`](crate::CacheLayer::dedup_bodies). The bodies are shared only
+    /// by the stores keeping them in memory, as they are.
+    pub fn dedup_bodies(mut self) -> Self {
+        self.config.body_index = Some(Arc::default());
+        self
+    }
+
+    /// Allow manual cache invalidation by setting the `X-Invalidate-Cache` header in the request.
+    pub fn allow_invalidation(mut self) -> Self {
+        self.config.allow_invalidation = true;
+        self
+    }
+
+    /// Allow the requests to set the TTL of their responses with the `X-Cache-TTL` header, see
+    /// [`CacheLayer::allow_ttl_override`](crate::CacheLayer::allow_ttl_override).
+    pub fn allow_ttl_override(mut self) -> Self {
+        self.config.allow_ttl_override = true;
+        self
+    }
+
+    /// Change the name of the request header invalidating the cache, see
+    /// [`CacheLayer::invalidation_header`](crate::CacheLayer::invalidation_header).
+    pub fn invalidation_header(mut self, name: HeaderName) -> Self {
+        self.config.invalidation_header = name;
+        self
+    }
+
+    /// Require the given value of the invalidation header, see
+    /// [`CacheLayer::invalidation_header_value`](crate::CacheLayer::invalidation_header_value).
+    pub fn invalidation_header_value(mut self, value: &str) -> Self {
+        self.config.invalidation_value = Some(value.into());
+        self
+    }
+
+    /// Add the value of the given request header to the tracing spans of the layer, see
+    /// [`CacheLayer::trace_request_id_header`](crate::CacheLayer::trace_request_id_header).
+    pub fn trace_request_id_header(mut self, name: HeaderName) -> Self {
+        self.config.request_id_header = Some(name);
+        self
+    }
+
+    /// Generate the ids of the requests without them, see
+    /// [`CacheLayer::generate_request_ids`](crate::CacheLayer::generate_request_ids).
+    pub fn generate_request_ids(mut self) -> Self {
+        self.config.generate_request_ids = true;
+        self.config
+            .request_id_header
+            .get_or_insert(HeaderName::from_static("x-request-id"));
+        self
+    }
+
+    /// Change the name of the response header with the age of the cached response, see
+    /// [`CacheLayer::age_header`](crate::CacheLayer::age_header).
+    pub fn age_header(mut self, name: HeaderName) -> Self {
+        self.config.age_header = name;
+        self
+    }
+
+    /// Choose the headers with the age of the cached response, see
+    /// [`CacheLayer::age_headers`](crate::CacheLayer::age_headers).
+    pub fn age_headers(mut self, headers: AgeHeader) -> Self {
+        self.config.age_headers = headers;
+        self
+    }
+
+    /// Allow bypassing the cache by setting the `X-Cache-Bypass` header in the request, see
+    /// [`CacheLayer::allow_bypass`](crate::CacheLayer::allow_bypass).
+    pub fn allow_bypass(mut self) -> Self {
+        self.config.allow_bypass = true;
+        self
+    }
+
+    /// Allow the response headers to be included in the cached response.
+    pub fn add_response_headers(mut self) -> Self {
+        self.config.add_response_headers = true;
+        self
+    }
+
+    /// Respect the `Cache-Control` header of the responses, see
+    /// [`CacheLayer::respect_cache_control`](crate::CacheLayer::respect_cache_control).
+    pub fn respect_cache_control(mut self) -> Self {
+        self.config.respect_cache_control = true;
+        self
+    }
+
+    /// Store only the responses with the `Content-Type` header, see
+    /// [`CacheLayer::require_content_type`](crate::CacheLayer::require_content_type).
+    pub fn require_content_type(mut self) -> Self {
+        self.config.require_content_type = true;
+        self
+    }
+
+    /// Refuse to store the responses already encoded by the wrapped service, see
+    /// [`CacheLayer::strict_encoding`](crate::CacheLayer::strict_encoding).
+    pub fn strict_encoding(mut self) -> Self {
+        self.config.strict_encoding = true;
+        self
+    }
+
+    /// Respect the `Cache-Control` header of the requests, see
+    /// [`CacheLayer::respect_request_cache_control`](crate::CacheLayer::respect_request_cache_control).
+    pub fn respect_request_cache_control(mut self) -> Self {
+        self.config.respect_request_cache_control = true;
+        self
+    }
+
+    /// Use the freshness lifetime declared by the response’s `Cache-Control` header, see
+    /// [`CacheLayer::honor_max_age`](crate::CacheLayer::honor_max_age).
+    pub fn honor_max_age(mut self) -> Self {
+        self.config.honor_max_age = true;
+        self
+    }
+
+    /// Generate `ETag`s and respond to matching conditional requests with `304 Not Modified`, see
+    /// [`CacheLayer::generate_etags`](crate::CacheLayer::generate_etags).
+    pub fn generate_etags(self) -> Self {
+        self.generate_etags_with(default_etag)
+    }
+
+    /// Like [`AsyncCacheLayer::generate_etags`], but with a custom function computing the opaque
+    /// tag from the body.
+    pub fn generate_etags_with(mut self, etag: fn(&[u8]) -> String) -> Self {
+        self.config.etag = Some(etag);
+        self
+    }
+
+    /// Respond with `304 Not Modified` to the requests not modified since the cached response, see
+    /// [`CacheLayer::honor_last_modified`](crate::CacheLayer::honor_last_modified).
+    pub fn honor_last_modified(mut self) -> Self {
+        self.config.honor_last_modified = true;
+        self
+    }
+
+    /// Respond with `304 Not Modified` to the requests matching the `ETag` of the cached response,
+    /// see [`CacheLayer::honor_etags`](crate::CacheLayer::honor_etags).
+    pub fn honor_etags(mut self) -> Self {
+        self.config.honor_etags = true;
+        self
+    }
+
+    /// Respond with `206 Partial Content` to the requests for a range of the cached bodies, see
+    /// [`CacheLayer::honor_ranges`](crate::CacheLayer::honor_ranges).
+    pub fn honor_ranges(mut self) -> Self {
+        self.config.honor_ranges = true;
+        self
+    }
+
+    /// Honor the `Vary` header of the responses, see
+    /// [`CacheLayer::honor_vary`](crate::CacheLayer::honor_vary).
+    pub fn honor_vary(mut self) -> Self {
+        self.config.honor_vary = true;
+        self
+    }
+
+    /// Cache the responses with the status codes accepted by the predicate, see
+    /// [`CacheLayer::cache_if`](crate::CacheLayer::cache_if).
+    pub fn cache_if(
+        mut self,
+        predicate: impl Fn(StatusCode) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.cacheable_status = CacheableStatus::Predicate(Hook(Arc::new(predicate)));
+        self
+    }
+
+    /// Cache the responses with the status codes in the range, see
+    /// [`CacheLayer::success_range`](crate::CacheLayer::success_range).
+    pub fn success_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.config.cacheable_status = CacheableStatus::Range(range);
+        self
+    }
+
+    /// Cache the responses with the status codes the function gives a TTL for, each for up to its
+    /// TTL, see [`CacheLayer::status_ttls`](crate::CacheLayer::status_ttls).
+    pub fn status_ttls(
+        mut self,
+        ttls: impl Fn(StatusCode) -> Option<Duration> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.cacheable_status = CacheableStatus::Ttls(Hook(Arc::new(ttls)));
+        self
+    }
+
+    /// Cache the `404 Not Found` and `410 Gone` responses for up to the given time, see
+    /// [`CacheLayer::negative_ttl`](crate::CacheLayer::negative_ttl).
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.config.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Use the cache only for the requests with the given methods, see
+    /// [`CacheLayer::cache_methods`](crate::CacheLayer::cache_methods).
+    pub fn cache_methods(mut self, methods: &[Method]) -> Self {
+        self.config.cacheable_methods = methods.into();
+        self
+    }
+
+    /// Remove the `Set-Cookie` headers from the responses before storing them, see
+    /// [`CacheLayer::strip_set_cookie`](crate::CacheLayer::strip_set_cookie).
+    pub fn strip_set_cookie(self) -> Self {
+        self.strip_headers(&[header::SET_COOKIE])
+    }
+
+    /// Remove the given headers from the responses before storing them, see
+    /// [`CacheLayer::strip_headers`](crate::CacheLayer::strip_headers).
+    pub fn strip_headers(mut self, names: &[HeaderName]) -> Self {
+        let mut stripped = self.config.stripped_headers.to_vec();
+        stripped.extend_from_slice(names);
+        self.config.stripped_headers = stripped.into();
+        self
+    }
+
+    /// Store only the given headers of the responses, see
+    /// [`CacheLayer::cache_header_allowlist`](crate::CacheLayer::cache_header_allowlist).
+    pub fn cache_header_allowlist(mut self, names: &[HeaderName]) -> Self {
+        self.config.allowed_headers = Some(names.into());
+        self
+    }
+
+    /// Use the cache only for the requests accepted by the predicate, see
+    /// [`CacheLayer::cache_request_if`](crate::CacheLayer::cache_request_if).
+    pub fn cache_request_if(
+        mut self,
+        predicate: impl Fn(&Request<Body>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.cacheable_request = Hook(Arc::new(predicate));
+        self
+    }
+
+    /// Use the cache only for the requests with the paths accepted by the predicate, see
+    /// [`CacheLayer::cache_paths`](crate::CacheLayer::cache_paths).
+    pub fn cache_paths(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.config.cacheable_path = Hook(Arc::new(predicate));
+        self
+    }
+
+    /// Store only the responses accepted by the predicate, see
+    /// [`CacheLayer::cache_response_if`](crate::CacheLayer::cache_response_if).
+    pub fn cache_response_if(
+        mut self,
+        predicate: impl Fn(&Parts, &Bytes) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.cacheable_body = Hook(Arc::new(predicate));
+        self
+    }
+
+    /// Transform the parts of the responses as they’re stored, see
+    /// [`CacheLayer::map_cached_response`](crate::CacheLayer::map_cached_response).
+    pub fn map_cached_response(
+        mut self,
+        transform: impl Fn(&mut Parts, &Bytes) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.map_stored = Some(Hook(Arc::new(transform)));
+        self
+    }
+
+    /// Transform every response served by the layer, see
+    /// [`CacheLayer::map_served_response`](crate::CacheLayer::map_served_response).
+    pub fn map_served_response(
+        mut self,
+        transform: impl Fn(&mut Response) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.map_served = Some(Hook(Arc::new(transform)));
+        self
+    }
+
+    /// Add the `Cache-Status` header to the responses, see
+    /// [`CacheLayer::add_cache_status`](crate::CacheLayer::add_cache_status).
+    pub fn add_cache_status(mut self) -> Self {
+        self.config.add_cache_status = true;
+        self
+    }
+
+    /// Add the `X-Cache-Key` header with the key of the request to the responses, see
+    /// [`CacheLayer::debug_key_header`](crate::CacheLayer::debug_key_header).
+    pub fn debug_key_header(mut self) -> Self {
+        self.config.debug_key_header = true;
+        self
+    }
+
+    /// Pass the responses with bodies over the limit through uncached, see
+    /// [`CacheLayer::passthrough_oversized`](crate::CacheLayer::passthrough_oversized).
+    pub fn passthrough_oversized(mut self) -> Self {
+        self.config.passthrough_oversized = true;
+        self
+    }
+
+    /// Replace the responses with bodies over the limit with the one made by the function, see
+    /// [`CacheLayer::oversized_response`](crate::CacheLayer::oversized_response).
+    pub fn oversized_response(
+        mut self,
+        response: impl Fn(usize) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.config.oversized_response = Hook(Arc::new(response));
+        self
+    }
+
+    /// Store the bodies of the responses compressed, see
+    /// [`CacheLayer::compress_body`](crate::CacheLayer::compress_body).
+    pub fn compress_body(mut self, compression: Compression) -> Self {
+        self.config.compression = Some(compression);
+        self
+    }
+
+    /// Store the bodies compressed with the given algorithms too, and serve the hits with the
+    /// ones accepted by the requests, see
+    /// [`CacheLayer::precompress`](crate::CacheLayer::precompress).
+    pub fn precompress(mut self, encodings: &[Compression]) -> Self {
+        self.config.encodings = encodings.into();
+        self
+    }
+
+    /// Measure the ages and the expiry of the cached responses with the given clock, see
+    /// [`CacheLayer::clock`](crate::CacheLayer::clock).
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.config.clock = Arc::new(clock);
+        self
+    }
+
+    /// Serve the cached bodies in chunks of up to the given size, see
+    /// [`CacheLayer::stream_hits`](crate::CacheLayer::stream_hits).
+    pub fn stream_hits(mut self, chunk_size: usize) -> Self {
+        self.config.hit_chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Choose how the failures of the store are handled: either by passing the requests to the
+    /// wrapped service as if their responses weren’t cached ([`StoreErrorPolicy::Degrade`], the
+    /// default), or by responding with `503 Service Unavailable` ([`StoreErrorPolicy::Fail`]),
+    /// eg. when the wrapped service can’t handle the load without the cache.
+    ///
+    /// ```rust
+    /// use std::{sync::Mutex, time::Duration};
+    /// use axum_response_cache::{AsyncCacheLayer, StoreErrorPolicy};
+    /// use cached::TimedCache;
+    ///
+    /// let layer = AsyncCacheLayer::with(Mutex::new(TimedCache::with_lifespan(Duration::from_secs(60))))
+    ///     .on_store_error(StoreErrorPolicy::Fail);
+    /// ```
+    ///
+    /// The policy applies to the failures of reading the cached responses, and of invalidating
+    /// them. The failures of storing the responses only leave them uncached, so they’re served
+    /// either way (and counted as not stored). The failures are always logged.
+    pub fn on_store_error(mut self, policy: StoreErrorPolicy) -> Self {
+        self.on_store_error = policy;
+        self
+    }
+
+    /// Get the response cached for the given key, see
+    /// [`CacheLayer::peek`](crate::CacheLayer::peek).
+    pub async fn peek(&self, key: &K::Key) -> Result<Option<CachedResponse>, CacheError<C::Error>> {
+        self.cache.get(key).await.map_err(CacheError::Read)
+    }
+
+    /// Remove the cached response for the given key.
+    pub async fn invalidate(&self, key: &K::Key) -> Result<(), CacheError<C::Error>> {
+        self.cache.remove(key).await.map_err(CacheError::Remove)?;
+        self.counters.invalidations.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache invalidated manually for key {:?}", key);
+        Ok(())
+    }
+
+    /// Get a snapshot of the statistics of the layer, see
+    /// [`CacheLayer::stats`](crate::CacheLayer::stats).
+    pub fn stats(&self) -> CacheStats {
+        self.counters.snapshot()
+    }
+
+    /// Get the flag enabling the cache, see
+    /// [`CacheLayer::enabled_handle`](crate::CacheLayer::enabled_handle).
+    pub fn enabled_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.config.enabled)
+    }
+
+    /// Use the given flag to enable the cache, see
+    /// [`CacheLayer::enabled_by`](crate::CacheLayer::enabled_by).
+    pub fn enabled_by(mut self, enabled: Arc<AtomicBool>) -> Self {
+        self.config.enabled = enabled;
+        self
+    }
+
+    /// Call the function with the key of each request served from the cache, see
+    /// [`CacheLayer::on_hit`](crate::CacheLayer::on_hit).
+    pub fn on_hit(mut self, callback: impl Fn(&K::Key) + Send + Sync + 'static) -> Self {
+        self.on_hit = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call the function with the key of each request passed to the wrapped service, see
+    /// [`CacheLayer::on_miss`](crate::CacheLayer::on_miss).
+    pub fn on_miss(mut self, callback: impl Fn(&K::Key) + Send + Sync + 'static) -> Self {
+        self.on_miss = Some(Arc::new(callback));
+        self
+    }
+}
+
+impl<C> AsyncCacheLayer<C, BasicKeyer>
+where
+    C: AsyncCache<crate::BasicKey>,
+{
+    /// Create a new cache layer with a given store and the default body size limit of 128 MB.
+    pub fn with(cache: C) -> Self {
+        Self::with_cache_and_keyer(cache, BasicKeyer)
+    }
+}
+
+impl<C, K> AsyncCacheLayer<C, K>
+where
+    C: AsyncCache<K::Key>,
+    K: Keyer,
+    K::Key: Debug,
+{
+    /// Logs the failure of the store, and makes the response to the request according to the
+    /// policy of the layer, if it’s not passed to the wrapped service.
+    fn store_failed(&self, key: &K::Key, err: CacheError<C::Error>) -> Option<Response> {
+        warn!("Store failure for key {:?}: {}", key, err);
+        match self.on_store_error {
+            StoreErrorPolicy::Degrade => None,
+            StoreErrorPolicy::Fail => Some(StatusCode::SERVICE_UNAVAILABLE.into_response()),
+        }
+    }
+}
+
+impl<S, C, K: Keyer> Layer<S> for AsyncCacheLayer<C, K> {
+    type Service = AsyncCacheService<S, C, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Self::Service {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service produced by [`AsyncCacheLayer`].
+pub struct AsyncCacheService<S, C, K: Keyer> {
+    inner: S,
+    layer: AsyncCacheLayer<C, K>,
+}
+
+impl<S, C, K> Clone for AsyncCacheService<S, C, K>
+where
+    S: Clone,
+    K: Keyer,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, C, K, B> Service<Request<Body>> for AsyncCacheService<S, C, K>
+where
+    S: Service<Request<Body>, Response = http::Response<B>> + Clone + Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    C: AsyncCache<K::Key> + Send + Sync + 'static,
+    K: Keyer + Send + Sync + 'static,
+    K::Key: Debug + Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send + 'static>>;
+
+    /// The service is always ready, as the cached responses are served without the wrapped
+    /// service. Its readiness is awaited only once it’s actually called.
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    #[instrument(skip(self, request), fields(request_id = tracing::field::Empty))]
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let request_id = self.layer.config.request_id(&mut request);
+        if let Some(id) = &request_id {
+            tracing::Span::current().record("request_id", id);
+        }
+        let inner = self.inner.clone();
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            while let Some(prepared) = layer.keyer.prepare(&mut request) {
+                request = prepared.await;
+            }
+            if layer.config.debug_key_header {
+                let keyer = &layer.keyer;
+                let key = keyer.get_key(&request).map(|key| keyer.key_to_string(&key));
+                let mut service = Self { inner, layer };
+                service.layer.config.debug_key_header = false;
+                let response = service.call(request).await?;
+                return Ok(with_key_header(response, key));
+            }
+            let mut config = layer.config.clone();
+            config.override_ttl(&request);
+            let key = layer
+                .keyer
+                .get_key(&request)
+                .filter(|_| !config.bypasses(&request));
+
+            let Some(key) = key else {
+                debug!("Bypassing the cache for the request");
+                let response = call_ready(inner, request)
+                    .instrument(inner_span(request_id.as_deref()))
+                    .await?;
+                let status = CacheStatus::forward("bypass");
+                layer.counters.record(status);
+                return Ok(status.mark(response, &config));
+            };
+
+            if config.invalidates(&request) {
+                if let Err(err) = layer.invalidate(&key).await {
+                    if let Some(response) = layer.store_failed(&key, err) {
+                        return Ok(CacheStatus::default().mark(response, &config));
+                    }
+                }
+            }
+
+            let (cached, status) = if config.revalidates(&request) {
+                debug!("Request asks for a fresh response, skipping the cached one");
+                (None, CacheStatus::forward("request"))
+            } else {
+                match layer.peek(&key).await {
+                    Ok(Some(entry)) => match entry.into_variant(request.headers()) {
+                        Some(value) if value.is_expired(config.clock.now()) => {
+                            (None, CacheStatus::forward("stale"))
+                        }
+                        Some(value) => (Some(value), CacheStatus::HIT),
+                        None => (None, CacheStatus::forward("vary-miss")),
+                    },
+                    Ok(None) => (None, CacheStatus::forward("miss")),
+                    Err(err) => match layer.store_failed(&key, err) {
+                        Some(response) => {
+                            return Ok(CacheStatus::default().mark(response, &config));
+                        }
+                        None => (None, CacheStatus::forward("miss")),
+                    },
+                }
+            };
+            let callback = if cached.is_some() {
+                &layer.on_hit
+            } else {
+                &layer.on_miss
+            };
+            if let Some(callback) = callback {
+                callback(&key);
+            }
+            if let Some(value) = cached {
+                layer.counters.record(status);
+                return Ok(status.mark(value.into_response_to(&request, &config), &config));
+            }
+
+            let request_headers = config.honor_vary.then(|| request.headers().clone());
+            let request = config.without_range(request);
+            let response = call_ready(inner, request)
+                .instrument(inner_span(request_id.as_deref()))
+                .await?;
+            if !config.caches_status(response.status()) {
+                layer.counters.record(status);
+                return Ok(status.mark(response, &config));
+            }
+
+            let mut value =
+                match cacheable_response(response, request_headers.as_ref(), &config).await {
+                    Ok(value) => value,
+                    Err(response) => {
+                        layer.counters.record(status);
+                        return Ok(status.mark(response, &config));
+                    }
+                };
+            if let Some(request_headers) = &request_headers {
+                if let Ok(Some(entry)) = layer.cache.get(&key).await {
+                    value.keep_alternatives(&entry, request_headers);
+                }
+            }
+            let stored = match layer.cache.set(key.clone(), value.stripped(&config)).await {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!(
+                        "Store failure for key {:?}: {}",
+                        key,
+                        CacheError::Write(err)
+                    );
+                    false
+                }
+            };
+            let status = CacheStatus { stored, ..status };
+            layer.counters.record(status);
+            Ok(status.mark(value.into_response_with(&config), &config))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicIsize, time::Duration};
+
+    use crate::axum::{extract::State, routing::get, Router};
+    use cached::TimedCache;
+    use tower::Service;
+
+    /// A store failing all the operations.
+    struct FailingStore;
+
+    impl<K: Send + Sync> AsyncCache<K> for FailingStore {
+        type Error = &'static str;
+
+        async fn get(&self, _key: &K) -> Result<Option<CachedResponse>, Self::Error> {
+            Err("store unavailable")
+        }
+
+        async fn set(&self, _key: K, _value: CachedResponse) -> Result<(), Self::Error> {
+            Err("store unavailable")
+        }
+
+        async fn remove(&self, _key: &K) -> Result<(), Self::Error> {
+            Err("store unavailable")
+        }
+    }
+
+    #[tokio::test]
+    async fn should_use_cached_value() {
+        let handler = |State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            StatusCode::OK
+        };
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        let cache = AsyncCacheLayer::with(Mutex::new(TimedCache::with_lifespan(
+            Duration::from_secs(60),
+        )));
+        let mut router = Router::new()
+            .route("/", get(handler).layer(cache))
+            .with_state(Arc::clone(&counter));
+
+        for _ in 0..10 {
+            let status = router
+                .call(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status();
+            assert!(status.is_success(), "handler should return success");
+        }
+
+        assert_eq!(
+            1,
+            counter.load(Ordering::Acquire),
+            "handler should’ve been called only once"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_pass_through_when_store_fails() {
+        let handler = |State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            StatusCode::OK
+        };
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        let cache = AsyncCacheLayer::with(FailingStore);
+        let mut router = Router::new()
+            .route("/", get(handler).layer(cache))
+            .with_state(Arc::clone(&counter));
+
+        for _ in 0..10 {
+            let status = router
+                .call(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status();
+            assert!(status.is_success(), "handler should return success");
+        }
+
+        assert_eq!(
+            10,
+            counter.load(Ordering::Acquire),
+            "handler should’ve been called for all requests"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_respond_with_503_when_store_fails_with_fail_policy() {
+        let handler = |State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            StatusCode::OK
+        };
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        let cache = AsyncCacheLayer::with(FailingStore).on_store_error(StoreErrorPolicy::Fail);
+        assert!(matches!(
+            cache.peek(&(Method::GET, "/".parse().unwrap())).await,
+            Err(CacheError::Read("store unavailable"))
+        ));
+        let mut router = Router::new()
+            .route("/", get(handler).layer(cache))
+            .with_state(Arc::clone(&counter));
+
+        let status = router
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!(
+            0,
+            counter.load(Ordering::Acquire),
+            "handler shouldn’t have been called"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Caching responses in memory up to a number of bytes, see [`BytesSizedCache`].
+
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use cached::{Cached, CloneCached};
+use tracing::debug;
+
+use crate::{CachedKeys, CachedResponse};
+
+/// The in-memory cache store limiting the total size of the cached bodies (rather than the number
+/// of the cached responses, like [`cached::SizedCache`]), for the responses varying in size a lot.
+///
+/// The least recently used responses are evicted when the bodies take more than the size limit;
+/// the responses with bodies over the whole limit aren’t stored at all. Only the bodies (of all
+/// the variants of a response, see [`CacheLayer::honor_vary`](crate::CacheLayer::honor_vary))
+/// count towards the limit, the headers don’t.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use axum_response_cache::{BytesSizedCache, CacheLayer};
+///
+/// // cache up to 64 MB of bodies for one minute
+/// let layer = CacheLayer::with(BytesSizedCache::with_size_and_lifespan(
+///     64 * 1024 * 1024,
+///     Duration::from_secs(60),
+/// ));
+/// ```
+#[derive(Clone, Debug)]
+pub struct BytesSizedCache<K> {
+    entries: HashMap<K, BytesSizedEntry>,
+    /// The keys ordered by their last use, the least recently used first.
+    recency: BTreeMap<u64, K>,
+    uses: u64,
+    size: usize,
+    size_limit: usize,
+    lifespan: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+struct BytesSizedEntry {
+    value: CachedResponse,
+    size: usize,
+    stored: Instant,
+    used: u64,
+}
+
+impl<K: Hash + Eq + Clone> BytesSizedCache<K> {
+    /// Create a new store keeping the bodies of up to `size` bytes in total.
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            uses: 0,
+            size: 0,
+            size_limit: size,
+            lifespan: None,
+        }
+    }
+
+    /// Create a new store keeping the bodies of up to `size` bytes in total, each for the given
+    /// time.
+    pub fn with_size_and_lifespan(size: usize, lifespan: Duration) -> Self {
+        Self {
+            lifespan: Some(lifespan),
+            ..Self::with_size(size)
+        }
+    }
+
+    /// The total size of the cached bodies.
+    pub fn size_in_bytes(&self) -> usize {
+        self.size
+    }
+
+    fn is_expired(&self, entry: &BytesSizedEntry) -> bool {
+        self.lifespan
+            .is_some_and(|lifespan| entry.stored.elapsed() >= lifespan)
+    }
+
+    fn touch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        self.recency.remove(&entry.used);
+        self.uses += 1;
+        entry.used = self.uses;
+        if let Some((key, _)) = self.entries.get_key_value(key) {
+            self.recency.insert(self.uses, key.clone());
+        }
+    }
+
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<BytesSizedEntry>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.used);
+        self.size -= entry.size;
+        Some(entry)
+    }
+
+    /// Remove the entry for the key if it’s expired, returning it.
+    fn remove_expired<Q>(&mut self, key: &Q) -> Option<BytesSizedEntry>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.get(key)?;
+        if self.is_expired(entry) {
+            self.remove_entry(key)
+        } else {
+            None
+        }
+    }
+
+    /// Insert the response as the most recently used one, and evict the least recently used other
+    /// ones until the bodies fit in the limit.
+    fn insert(&mut self, key: K, value: CachedResponse) -> Option<CachedResponse> {
+        let previous = self.remove_entry(&key).map(|entry| entry.value);
+        let size = value.body_size();
+        while self.size + size > self.size_limit {
+            let Some((_, evicted)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&evicted) {
+                self.size -= entry.size;
+            }
+        }
+        self.uses += 1;
+        self.recency.insert(self.uses, key.clone());
+        self.entries.insert(
+            key,
+            BytesSizedEntry {
+                value,
+                size,
+                stored: Instant::now(),
+                used: self.uses,
+            },
+        );
+        self.size += size;
+        previous
+    }
+}
+
+impl<K: Hash + Eq + Clone> Cached<K, CachedResponse> for BytesSizedCache<K> {
+    fn cache_get<Q>(&mut self, k: &Q) -> Option<&CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_expired(k);
+        self.touch(k);
+        self.entries.get(k).map(|entry| &entry.value)
+    }
+
+    fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_expired(k);
+        self.touch(k);
+        self.entries.get_mut(k).map(|entry| &mut entry.value)
+    }
+
+    fn cache_set(&mut self, k: K, v: CachedResponse) -> Option<CachedResponse> {
+        if v.body_size() > self.size_limit {
+            debug!("Response body over the size limit of the store, not storing it.");
+            return self.remove_entry(&k).map(|entry| entry.value);
+        }
+        self.insert(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> CachedResponse>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> &mut CachedResponse {
+        match self.cache_try_get_or_set_with(k, || Ok::<_, Infallible>(f())) {
+            Ok(value) => value,
+            Err(err) => match err {},
+        }
+    }
+
+    fn cache_try_get_or_set_with<F: FnOnce() -> Result<CachedResponse, E>, E>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> Result<&mut CachedResponse, E> {
+        self.remove_expired(&k);
+        if self.entries.contains_key(&k) {
+            self.touch(&k);
+        } else {
+            // the responses over the limit are stored anyway, as they have to be returned
+            self.insert(k.clone(), f()?);
+        }
+        Ok(&mut self
+            .entries
+            .get_mut(&k)
+            .expect("entry was just looked up or inserted")
+            .value)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(k).map(|entry| entry.value)
+    }
+
+    fn cache_clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.size = 0;
+    }
+
+    fn cache_reset(&mut self) {
+        self.entries = HashMap::new();
+        self.recency = BTreeMap::new();
+        self.size = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn cache_lifespan(&self) -> Option<Duration> {
+        self.lifespan
+    }
+
+    fn cache_set_lifespan(&mut self, ttl: Duration) -> Option<Duration> {
+        self.lifespan.replace(ttl)
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<Duration> {
+        self.lifespan.take()
+    }
+}
+
+impl<K: Hash + Eq + Clone> CloneCached<K, CachedResponse> for BytesSizedCache<K> {
+    fn cache_get_expired<Q>(&mut self, k: &Q) -> (Option<CachedResponse>, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(entry) = self.remove_expired(k) {
+            return (Some(entry.value), true);
+        }
+        self.touch(k);
+        (self.entries.get(k).map(|entry| entry.value.clone()), false)
+    }
+}
+
+impl<K: Hash + Eq + Clone> CachedKeys<K> for BytesSizedCache<K> {
+    fn cache_keys(&self) -> Vec<K> {
+        self.recency.values().cloned().collect()
+    }
+
+    fn cache_peek(&self, key: &K) -> Option<&CachedResponse> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axum::{body::Bytes, http::Response};
+
+    fn response(body: &'static str) -> CachedResponse {
+        let (parts, ()) = Response::new(()).into_parts();
+        CachedResponse::new(parts, Bytes::from_static(body.as_bytes()))
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_responses() {
+        let mut cache = BytesSizedCache::with_size(10);
+        cache.cache_set("a", response("aaaa"));
+        cache.cache_set("b", response("bbbb"));
+        assert!(cache.cache_get("a").is_some(), "`a` should be cached");
+
+        // `b` is evicted, as `a` was used more recently
+        cache.cache_set("c", response("cccc"));
+        assert_eq!(8, cache.size_in_bytes());
+        assert!(cache.cache_get("b").is_none(), "`b` should’ve been evicted");
+
+        // both `a` and `c` are evicted to make room
+        cache.cache_set("d", response("dddddddd"));
+        assert_eq!(8, cache.size_in_bytes());
+        assert_eq!(1, cache.cache_size());
+        assert!(cache.cache_get("d").is_some(), "`d` should be cached");
+
+        // the responses over the whole limit aren’t stored, and replace the old ones
+        cache.cache_set("d", response("over the limit"));
+        assert_eq!(0, cache.size_in_bytes());
+        assert!(
+            cache.cache_get("d").is_none(),
+            "`d` should’ve been rejected"
+        );
+    }
+}
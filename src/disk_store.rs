@@ -0,0 +1,481 @@
+//! Caching response bodies on disk, see [`DiskStore`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    future::Future,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::axum::body::Bytes;
+use crate::{lock, AsyncCache, CachedResponse};
+
+/// The [`AsyncCache`] keeping the bodies of the responses in files, and only their headers (and
+/// the other metadata) in memory. It’s meant for large responses, eg. the static files served with
+/// `ServeDir`, that would take too much memory in the other stores.
+///
+/// The bodies are written to a directory created by the store (in the system’s temporary
+/// directory by default), which is removed along with all the files when the store is dropped.
+/// The responses expire after the lifespan of the store (or earlier, according to their own
+/// freshness lifetime, see [`CacheLayer::honor_max_age`](crate::CacheLayer::honor_max_age)), and
+/// the least recently used ones are evicted when the bodies take more than the size limit of the
+/// store (1 GB by default). The size of the single bodies is still limited by the layer, see
+/// [`AsyncCacheLayer::body_limit`](crate::AsyncCacheLayer::body_limit).
+///
+/// ```rust,no_run
+/// # use axum_08 as axum;
+/// use std::time::Duration;
+/// use axum::Router;
+/// use axum_response_cache::{AsyncCacheLayer, DiskStore};
+/// # use axum::routing::get;
+///
+/// let store = DiskStore::new(Duration::from_secs(3600))
+///     .unwrap()
+///     // keep at most 10 GB of bodies on disk
+///     .size_limit(10 * 1024 * 1024 * 1024);
+/// let router: Router = Router::new()
+///     # .route("/", get(|| async { "Hello, world!" }))
+///     // .nest_service("/", ServeDir::new("static/"))
+///     .layer(AsyncCacheLayer::with(store));
+/// ```
+pub struct DiskStore<K> {
+    directory: PathBuf,
+    lifespan: Duration,
+    size_limit: u64,
+    state: Mutex<DiskState<K>>,
+    files: AtomicU64,
+}
+
+/// The in-memory index of the files of a [`DiskStore`].
+struct DiskState<K> {
+    entries: HashMap<K, DiskEntry>,
+    /// The keys ordered by their last use, the least recently used first.
+    recency: BTreeMap<u64, K>,
+    uses: u64,
+    size: u64,
+}
+
+/// The response with its bodies (of all its variants) stored in a file.
+struct DiskEntry {
+    /// The response with the bodies left out.
+    metadata: CachedResponse,
+    path: PathBuf,
+    /// The lengths of the bodies in the file, the response’s own first, then the alternatives’
+    /// (each one followed by the ones of its encodings).
+    lengths: Vec<usize>,
+    size: u64,
+    expires: Instant,
+    used: u64,
+}
+
+impl<K> DiskStore<K> {
+    /// Create a new store in the system’s temporary directory, keeping the responses for the given
+    /// time.
+    pub fn new(lifespan: Duration) -> io::Result<Self> {
+        Self::in_directory(std::env::temp_dir(), lifespan)
+    }
+
+    /// Create a new store in a directory created within the given one, keeping the responses for
+    /// the given time.
+    pub fn in_directory(parent: impl AsRef<Path>, lifespan: Duration) -> io::Result<Self> {
+        static STORES: AtomicU64 = AtomicU64::new(0);
+        let directory = parent.as_ref().join(format!(
+            "axum-response-cache-{}-{}",
+            std::process::id(),
+            STORES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            lifespan,
+            size_limit: 1024 * 1024 * 1024,
+            state: Mutex::new(DiskState {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                uses: 0,
+                size: 0,
+            }),
+            files: AtomicU64::new(0),
+        })
+    }
+
+    /// Change the maximum total size of the bodies kept on disk. The responses with bodies over the
+    /// limit aren’t stored at all.
+    pub fn size_limit(mut self, new_limit: u64) -> Self {
+        self.size_limit = new_limit;
+        self
+    }
+
+    /// The directory with the files of the store.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+}
+
+impl<K> Drop for DiskStore<K> {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.directory) {
+            debug!(
+                "Failed to remove the cache directory {}: {}",
+                self.directory.display(),
+                err
+            );
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> DiskState<K> {
+    /// Remove the entry for the key, returning the path of its file to be removed.
+    fn remove(&mut self, key: &K) -> Option<PathBuf> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.used);
+        self.size -= entry.size;
+        Some(entry.path)
+    }
+
+    /// Evict the least recently used entries until the bodies fit in the limit, returning the
+    /// paths of their files to be removed.
+    fn evict(&mut self, size_limit: u64) -> Vec<PathBuf> {
+        let mut evicted = Vec::new();
+        while self.size > size_limit {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.size;
+                evicted.push(entry.path);
+            }
+        }
+        evicted
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            self.uses += 1;
+            entry.used = self.uses;
+            self.recency.insert(self.uses, key.clone());
+        }
+    }
+}
+
+/// Remove the files of the entries no longer in the store.
+async fn remove_files(paths: impl IntoIterator<Item = PathBuf>) {
+    for path in paths {
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            debug!(
+                "Failed to remove the cached file {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Split the bodies of the response and its alternatives (along with their pre-compressed
+/// encodings) off it.
+fn split_bodies(value: CachedResponse) -> (CachedResponse, Vec<Bytes>) {
+    let mut bodies = Vec::new();
+    let mut value = take_bodies(value, &mut bodies);
+    value.alternatives = value
+        .alternatives
+        .iter()
+        .map(|alternative| take_bodies(alternative.clone(), &mut bodies))
+        .collect();
+    (value, bodies)
+}
+
+/// Move the body of the response, then the ones of its encodings, to the bodies.
+fn take_bodies(mut value: CachedResponse, bodies: &mut Vec<Bytes>) -> CachedResponse {
+    bodies.push(std::mem::take(&mut value.body));
+    value.encodings = value
+        .encodings
+        .iter()
+        .map(|(compression, body)| {
+            bodies.push(body.clone());
+            (*compression, Bytes::new())
+        })
+        .collect();
+    value
+}
+
+/// Put the bodies read from the file back into the response and its alternatives.
+fn join_bodies(value: CachedResponse, data: Bytes, lengths: &[usize]) -> CachedResponse {
+    let mut offset = 0;
+    let mut bodies = lengths.iter().map(|length| {
+        let body = data.slice(offset..offset + length);
+        offset += length;
+        body
+    });
+    let mut value = put_bodies(value, &mut bodies);
+    value.alternatives = value
+        .alternatives
+        .iter()
+        .map(|alternative| put_bodies(alternative.clone(), &mut bodies))
+        .collect();
+    value
+}
+
+/// Put the next bodies back into the response, then into its encodings.
+fn put_bodies(
+    mut value: CachedResponse,
+    bodies: &mut impl Iterator<Item = Bytes>,
+) -> CachedResponse {
+    value.body = bodies.next().unwrap_or_default();
+    value.encodings = value
+        .encodings
+        .iter()
+        .map(|(compression, _)| (*compression, bodies.next().unwrap_or_default()))
+        .collect();
+    value
+}
+
+impl<K> AsyncCache<K> for DiskStore<K>
+where
+    K: Debug + Hash + Eq + Clone + Send + Sync,
+{
+    type Error = io::Error;
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send {
+        let found = {
+            let mut state = lock(&self.state);
+            match state.entries.get(key) {
+                Some(entry) if entry.expires <= Instant::now() => Err(state.remove(key)),
+                Some(entry) => {
+                    let found = (
+                        entry.metadata.clone(),
+                        entry.path.clone(),
+                        entry.lengths.clone(),
+                    );
+                    state.touch(key);
+                    Ok(found)
+                }
+                None => Err(None),
+            }
+        };
+        async move {
+            let (metadata, path, lengths) = match found {
+                Ok(found) => found,
+                Err(expired) => {
+                    remove_files(expired).await;
+                    return Ok(None);
+                }
+            };
+            match tokio::fs::read(&path).await {
+                Ok(data) => Ok(Some(join_bodies(metadata, data.into(), &lengths))),
+                // the entry was replaced or evicted in the meantime
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            }
+        }
+    }
+
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let path = self.directory.join(format!(
+            "{}.body",
+            self.files.fetch_add(1, Ordering::Relaxed)
+        ));
+        let lifespan = value.expires.map_or(self.lifespan, |expires| {
+            expires
+                .saturating_duration_since(Instant::now())
+                .min(self.lifespan)
+        });
+        let (metadata, bodies) = split_bodies(value);
+        let lengths: Vec<_> = bodies.iter().map(Bytes::len).collect();
+        let size = lengths.iter().sum::<usize>() as u64;
+        async move {
+            if size > self.size_limit {
+                debug!(
+                    "Response for key {:?} over the size limit of the store",
+                    key
+                );
+                let removed = lock(&self.state).remove(&key);
+                remove_files(removed).await;
+                return Ok(());
+            }
+            tokio::fs::write(&path, bodies.concat()).await?;
+
+            let removed = {
+                let mut state = lock(&self.state);
+                let mut removed: Vec<_> = state.remove(&key).into_iter().collect();
+                state.uses += 1;
+                let used = state.uses;
+                state.recency.insert(used, key.clone());
+                state.entries.insert(
+                    key,
+                    DiskEntry {
+                        metadata,
+                        path,
+                        lengths,
+                        size,
+                        expires: Instant::now() + lifespan,
+                        used,
+                    },
+                );
+                state.size += size;
+                removed.extend(state.evict(self.size_limit));
+                removed
+            };
+            remove_files(removed).await;
+            Ok(())
+        }
+    }
+
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let removed = lock(&self.state).remove(key);
+        async move {
+            remove_files(removed).await;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{atomic::AtomicIsize, Arc};
+
+    use crate::axum::{
+        body::{self, Body},
+        extract::State,
+        http::{Request, Uri},
+        routing::get,
+        Router,
+    };
+    use crate::AsyncCacheLayer;
+    use tower::Service;
+
+    #[tokio::test]
+    async fn should_serve_cached_bodies_from_disk() {
+        let handler = |State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            "Hello, world!"
+        };
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        let store = DiskStore::new(Duration::from_secs(60)).unwrap();
+        let directory = store.directory().to_owned();
+        let mut router = Router::new()
+            .route("/", get(handler).layer(AsyncCacheLayer::with(store)))
+            .with_state(Arc::clone(&counter));
+
+        for _ in 0..10 {
+            let response = router
+                .call(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(
+                response.status().is_success(),
+                "handler should return success"
+            );
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!("Hello, world!", body, "cached body should be served");
+        }
+        assert_eq!(
+            1,
+            counter.load(Ordering::Acquire),
+            "handler should’ve been called only once"
+        );
+        assert_eq!(
+            1,
+            std::fs::read_dir(&directory).unwrap().count(),
+            "body should’ve been written to a file"
+        );
+
+        drop(router);
+        assert!(
+            !directory.exists(),
+            "directory should’ve been removed with the store"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_evict_least_recently_used_bodies() {
+        let handler = |uri: Uri, State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            uri.path().repeat(4)
+        };
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        // fits the bodies of two responses
+        let store = DiskStore::new(Duration::from_secs(60))
+            .unwrap()
+            .size_limit(16);
+        let directory = store.directory().to_owned();
+        let mut router = Router::new()
+            .route("/a", get(handler))
+            .route("/b", get(handler))
+            .route("/c", get(handler))
+            .layer(AsyncCacheLayer::with(store))
+            .with_state(Arc::clone(&counter));
+
+        for path in ["/a", "/b", "/a", "/c", "/a", "/b"] {
+            let status = router
+                .call(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status();
+            assert!(status.is_success(), "handler should return success");
+        }
+
+        // `/b` was evicted by `/c`, as `/a` was used more recently
+        assert_eq!(
+            4,
+            counter.load(Ordering::Acquire),
+            "handler should’ve been called for the evicted responses"
+        );
+        assert_eq!(
+            2,
+            std::fs::read_dir(&directory).unwrap().count(),
+            "files of the evicted responses should’ve been removed"
+        );
+    }
+
+    #[test]
+    fn should_spill_bodies_of_encodings() {
+        let response = |body: &'static str| {
+            let (parts, ()) = crate::axum::http::Response::new(()).into_parts();
+            let mut response = CachedResponse::new(parts, Bytes::from_static(body.as_bytes()));
+            response.encodings = [(crate::Compression::Gzip, Bytes::from(format!("{body}.gz")))]
+                .into_iter()
+                .collect();
+            response
+        };
+        let mut value = response("en");
+        value.alternatives = [response("fr")].into_iter().collect();
+
+        let (metadata, bodies) = split_bodies(value);
+        assert_eq!(["en", "en.gz", "fr", "fr.gz"], bodies.as_slice());
+        assert!(metadata.body.is_empty());
+        assert!(
+            metadata.encodings[0].1.is_empty(),
+            "encoding should be spilled"
+        );
+        assert!(metadata.alternatives[0].encodings[0].1.is_empty());
+
+        let lengths: Vec<_> = bodies.iter().map(Bytes::len).collect();
+        let value = join_bodies(metadata, bodies.concat().into(), &lengths);
+        assert_eq!("en", value.body);
+        assert_eq!("en.gz", value.encodings[0].1);
+        assert_eq!("fr", value.alternatives[0].body);
+        assert_eq!("fr.gz", value.alternatives[0].encodings[0].1);
+    }
+}
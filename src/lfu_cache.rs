@@ -0,0 +1,399 @@
+//! Caching responses in memory with the least frequently used ones evicted, see [`LfuCache`].
+
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    hash::{BuildHasher, Hash, RandomState},
+    time::{Duration, Instant},
+};
+
+use cached::{Cached, CloneCached};
+use tracing::debug;
+
+use crate::{CachedKeys, CachedResponse};
+
+/// The in-memory cache store keeping up to a number of responses, evicting the least frequently
+/// used ones (rather than the least recently used ones, like [`cached::SizedCache`]), for the
+/// workloads with a handful of hot keys and a long tail of the cold ones.
+///
+/// The uses of all the keys, including the ones not stored (anymore), are counted in a compact
+/// frequency sketch, periodically halved so that the keys that used to be hot cool down. Once the
+/// store is full, a new response is stored only if its key is used more frequently than the one
+/// of the response it would evict (see the TinyLFU admission policy), so that a burst of the cold
+/// keys doesn’t flush the hot ones.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use axum_response_cache::{CacheLayer, LfuCache};
+///
+/// // cache up to 1000 responses for one minute
+/// let layer = CacheLayer::with(LfuCache::with_size_and_lifespan(1000, Duration::from_secs(60)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LfuCache<K> {
+    entries: HashMap<K, LfuEntry>,
+    /// The keys ordered by their use counts, and then their last use, the least frequently used
+    /// first.
+    frequency: BTreeMap<(u64, u64), K>,
+    sketch: FrequencySketch,
+    hasher: RandomState,
+    uses: u64,
+    size_limit: usize,
+    lifespan: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+struct LfuEntry {
+    value: CachedResponse,
+    stored: Instant,
+    /// The position of the entry in the frequency order.
+    rank: (u64, u64),
+}
+
+impl<K: Hash + Eq + Clone> LfuCache<K> {
+    /// Create a new store keeping up to `size` responses.
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            frequency: BTreeMap::new(),
+            sketch: FrequencySketch::with_capacity(size),
+            hasher: RandomState::new(),
+            uses: 0,
+            size_limit: size,
+            lifespan: None,
+        }
+    }
+
+    /// Create a new store keeping up to `size` responses, each for the given time.
+    pub fn with_size_and_lifespan(size: usize, lifespan: Duration) -> Self {
+        Self {
+            lifespan: Some(lifespan),
+            ..Self::with_size(size)
+        }
+    }
+
+    fn is_expired(&self, entry: &LfuEntry) -> bool {
+        self.lifespan
+            .is_some_and(|lifespan| entry.stored.elapsed() >= lifespan)
+    }
+
+    /// Count the use of the key, moving its entry (if any) up the frequency order.
+    fn touch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.sketch.increment(self.hasher.hash_one(key));
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        self.frequency.remove(&entry.rank);
+        self.uses += 1;
+        entry.rank = (entry.rank.0 + 1, self.uses);
+        let rank = entry.rank;
+        if let Some((key, _)) = self.entries.get_key_value(key) {
+            self.frequency.insert(rank, key.clone());
+        }
+    }
+
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<LfuEntry>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.frequency.remove(&entry.rank);
+        Some(entry)
+    }
+
+    /// Remove the entry for the key if it’s expired, returning it.
+    fn remove_expired<Q>(&mut self, key: &Q) -> Option<LfuEntry>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.get(key)?;
+        if self.is_expired(entry) {
+            self.remove_entry(key)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the new key is used frequently enough to evict the least frequently used one, if
+    /// the store is full.
+    fn admits(&self, key: &K) -> bool {
+        if self.entries.len() < self.size_limit {
+            return true;
+        }
+        let Some((_, victim)) = self.frequency.first_key_value() else {
+            return false;
+        };
+        self.sketch.estimate(self.hasher.hash_one(key))
+            > self.sketch.estimate(self.hasher.hash_one(victim))
+    }
+
+    /// Insert the response, evicting the least frequently used ones until it fits in the limit.
+    /// The replaced response keeps its use count.
+    fn insert(&mut self, key: K, value: CachedResponse) -> Option<CachedResponse> {
+        let previous = self.remove_entry(&key);
+        while self.entries.len() >= self.size_limit {
+            let Some((_, evicted)) = self.frequency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&evicted);
+        }
+        self.uses += 1;
+        let rank = (previous.as_ref().map_or(1, |entry| entry.rank.0), self.uses);
+        self.frequency.insert(rank, key.clone());
+        self.entries.insert(
+            key,
+            LfuEntry {
+                value,
+                stored: Instant::now(),
+                rank,
+            },
+        );
+        previous.map(|entry| entry.value)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Cached<K, CachedResponse> for LfuCache<K> {
+    fn cache_get<Q>(&mut self, k: &Q) -> Option<&CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_expired(k);
+        self.touch(k);
+        self.entries.get(k).map(|entry| &entry.value)
+    }
+
+    fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_expired(k);
+        self.touch(k);
+        self.entries.get_mut(k).map(|entry| &mut entry.value)
+    }
+
+    fn cache_set(&mut self, k: K, v: CachedResponse) -> Option<CachedResponse> {
+        if !self.entries.contains_key(&k) && !self.admits(&k) {
+            debug!("Key used less frequently than the ones in the store, not storing it.");
+            return None;
+        }
+        self.insert(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> CachedResponse>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> &mut CachedResponse {
+        match self.cache_try_get_or_set_with(k, || Ok::<_, Infallible>(f())) {
+            Ok(value) => value,
+            Err(err) => match err {},
+        }
+    }
+
+    fn cache_try_get_or_set_with<F: FnOnce() -> Result<CachedResponse, E>, E>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> Result<&mut CachedResponse, E> {
+        self.remove_expired(&k);
+        self.touch(&k);
+        if !self.entries.contains_key(&k) {
+            // the keys not admitted are stored anyway, as their responses have to be returned
+            self.insert(k.clone(), f()?);
+        }
+        Ok(&mut self
+            .entries
+            .get_mut(&k)
+            .expect("entry was just looked up or inserted")
+            .value)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(k).map(|entry| entry.value)
+    }
+
+    fn cache_clear(&mut self) {
+        self.entries.clear();
+        self.frequency.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.entries = HashMap::new();
+        self.frequency = BTreeMap::new();
+        self.sketch = FrequencySketch::with_capacity(self.size_limit);
+    }
+
+    fn cache_size(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn cache_lifespan(&self) -> Option<Duration> {
+        self.lifespan
+    }
+
+    fn cache_set_lifespan(&mut self, ttl: Duration) -> Option<Duration> {
+        self.lifespan.replace(ttl)
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<Duration> {
+        self.lifespan.take()
+    }
+}
+
+impl<K: Hash + Eq + Clone> CloneCached<K, CachedResponse> for LfuCache<K> {
+    fn cache_get_expired<Q>(&mut self, k: &Q) -> (Option<CachedResponse>, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(entry) = self.remove_expired(k) {
+            self.sketch.increment(self.hasher.hash_one(k));
+            return (Some(entry.value), true);
+        }
+        self.touch(k);
+        (self.entries.get(k).map(|entry| entry.value.clone()), false)
+    }
+}
+
+impl<K: Hash + Eq + Clone> CachedKeys<K> for LfuCache<K> {
+    fn cache_keys(&self) -> Vec<K> {
+        self.frequency.values().cloned().collect()
+    }
+
+    fn cache_peek(&self, key: &K) -> Option<&CachedResponse> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+}
+
+/// The approximate use counts of the keys (see the count–min sketch), up to 15 each.
+#[derive(Clone, Debug)]
+struct FrequencySketch {
+    counters: Vec<u8>,
+    /// The number of uses counted since the counters were last halved.
+    additions: usize,
+    /// The number of uses after which the counters are halved.
+    period: usize,
+}
+
+impl FrequencySketch {
+    /// The seeds of the hashes of the rows of counters.
+    const SEEDS: [u64; 4] = [
+        0x9e37_79b9_7f4a_7c15,
+        0xc2b2_ae3d_27d4_eb4f,
+        0x1656_67b1_9e37_79f9,
+        0x27d4_eb2f_1656_67c5,
+    ];
+    const MAX: u8 = 15;
+
+    fn with_capacity(capacity: usize) -> Self {
+        // the small stores still get enough counters to keep the collisions of the keys rare
+        let width = capacity.max(64).next_power_of_two();
+        Self {
+            counters: vec![0; width * Self::SEEDS.len()],
+            additions: 0,
+            period: width * 10,
+        }
+    }
+
+    /// The indices of the counters of the hash, one in each row.
+    fn indices(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let width = self.counters.len() / Self::SEEDS.len();
+        Self::SEEDS.into_iter().enumerate().map(move |(row, seed)| {
+            let column = (hash.wrapping_mul(seed) >> 32) as usize & (width - 1);
+            row * width + column
+        })
+    }
+
+    fn increment(&mut self, hash: u64) {
+        for index in self.indices(hash).collect::<Vec<_>>() {
+            let counter = &mut self.counters[index];
+            *counter = (*counter + 1).min(Self::MAX);
+        }
+        self.additions += 1;
+        if self.additions >= self.period {
+            for counter in &mut self.counters {
+                *counter /= 2;
+            }
+            self.additions /= 2;
+        }
+    }
+
+    fn estimate(&self, hash: u64) -> u8 {
+        self.indices(hash)
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axum::{body::Bytes, http::Response};
+
+    fn response(body: &'static str) -> CachedResponse {
+        let (parts, ()) = Response::new(()).into_parts();
+        CachedResponse::new(parts, Bytes::from_static(body.as_bytes()))
+    }
+
+    #[test]
+    fn should_keep_hot_keys_while_evicting_cold_ones() {
+        let mut cache = LfuCache::with_size(3);
+        cache.cache_set("hot", response("hot"));
+        for _ in 0..5 {
+            assert!(cache.cache_get("hot").is_some(), "hot key should be cached");
+        }
+
+        // a long tail of the cold keys, each requested twice, fills up the store
+        for cold in ["a", "b", "c", "d", "e", "f", "g"] {
+            if cache.cache_get(cold).is_none() {
+                cache.cache_set(cold, response(cold));
+            }
+            cache.cache_get(cold);
+        }
+        assert_eq!(3, cache.cache_size());
+        assert!(cache.cache_get("hot").is_some(), "hot key should survive");
+
+        // a key requested much more often evicts the least frequently used one (by a margin wide
+        // enough for the collisions in the sketch, whose hashes are seeded randomly)
+        for _ in 0..10 {
+            cache.cache_get("warm");
+        }
+        cache.cache_set("warm", response("warm"));
+        assert_eq!(3, cache.cache_size());
+        assert!(
+            cache.cache_get("warm").is_some(),
+            "warm key should be admitted"
+        );
+        assert!(
+            cache.cache_get("a").is_none(),
+            "cold key should’ve been evicted"
+        );
+        assert!(cache.cache_get("hot").is_some(), "hot key should survive");
+
+        // the keys used less than the least frequently used one aren’t stored at all
+        for key in cache.cache_keys() {
+            for _ in 0..5 {
+                cache.cache_get(key);
+            }
+        }
+        cache.cache_set("once", response("once"));
+        assert!(
+            cache.cache_get("once").is_none(),
+            "rare key shouldn’t be admitted"
+        );
+    }
+}
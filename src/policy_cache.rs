@@ -0,0 +1,312 @@
+//! Choosing the cache store by its eviction policy, see [`EvictionPolicy`].
+
+use std::{borrow::Borrow, fmt::Debug, hash::Hash, time::Duration};
+
+use cached::{Cached, CloneCached, SizedCache, TimedCache};
+
+use crate::{
+    BasicKeyer, BytesSizedCache, CacheConfig, CacheLayer, CachedKeys, CachedResponse, Keyer,
+};
+
+/// The common ways of bounding the responses kept in memory, each backed by one of the stores of
+/// the crate or of the [`cached`] crate, see [`CacheLayer::builder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Keep each response for the given time, with no limit on their number (see
+    /// [`TimedCache`]).
+    Ttl(Duration),
+    /// Keep up to the given number of responses, evicting the least recently used ones (see
+    /// [`SizedCache`]).
+    Lru(usize),
+    /// Keep the bodies of up to the given number of bytes in total, evicting the least recently
+    /// used ones (see [`BytesSizedCache`]).
+    SizeBytes(usize),
+    /// Keep each response for the given time, and the bodies of up to the given number of bytes
+    /// in total (see [`BytesSizedCache`]).
+    TtlAndSize {
+        /// The time each response is kept for.
+        ttl: Duration,
+        /// The total size of the bodies kept.
+        bytes: usize,
+    },
+}
+
+/// The store made for an [`EvictionPolicy`], see [`CacheLayer::builder`].
+#[derive(Clone, Debug)]
+pub struct PolicyCache<K>(Store<K>);
+
+#[derive(Clone, Debug)]
+enum Store<K> {
+    Ttl(TimedCache<K, CachedResponse>),
+    Lru(SizedCache<K, CachedResponse>),
+    Bytes(BytesSizedCache<K>),
+}
+
+/// Calls the same method of the store, whichever it is.
+macro_rules! dispatch {
+    ($self:expr, $store:ident => $call:expr) => {
+        match $self {
+            Store::Ttl($store) => $call,
+            Store::Lru($store) => $call,
+            Store::Bytes($store) => $call,
+        }
+    };
+}
+
+impl<K: Hash + Eq + Clone> PolicyCache<K> {
+    /// Create a new store bounded according to the policy.
+    pub fn new(policy: EvictionPolicy) -> Self {
+        Self(match policy {
+            EvictionPolicy::Ttl(ttl) => Store::Ttl(TimedCache::with_lifespan(ttl)),
+            EvictionPolicy::Lru(count) => Store::Lru(SizedCache::with_size(count.max(1))),
+            EvictionPolicy::SizeBytes(bytes) => Store::Bytes(BytesSizedCache::with_size(bytes)),
+            EvictionPolicy::TtlAndSize { ttl, bytes } => {
+                Store::Bytes(BytesSizedCache::with_size_and_lifespan(bytes, ttl))
+            }
+        })
+    }
+}
+
+impl<K: Hash + Eq + Clone> Cached<K, CachedResponse> for PolicyCache<K> {
+    fn cache_get<Q>(&mut self, k: &Q) -> Option<&CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(&mut self.0, store => store.cache_get(k))
+    }
+
+    fn cache_get_mut<Q>(&mut self, k: &Q) -> Option<&mut CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(&mut self.0, store => store.cache_get_mut(k))
+    }
+
+    fn cache_set(&mut self, k: K, v: CachedResponse) -> Option<CachedResponse> {
+        dispatch!(&mut self.0, store => store.cache_set(k, v))
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> CachedResponse>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> &mut CachedResponse {
+        dispatch!(&mut self.0, store => store.cache_get_or_set_with(k, f))
+    }
+
+    fn cache_try_get_or_set_with<F: FnOnce() -> Result<CachedResponse, E>, E>(
+        &mut self,
+        k: K,
+        f: F,
+    ) -> Result<&mut CachedResponse, E> {
+        dispatch!(&mut self.0, store => store.cache_try_get_or_set_with(k, f))
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<CachedResponse>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        dispatch!(&mut self.0, store => store.cache_remove(k))
+    }
+
+    fn cache_clear(&mut self) {
+        dispatch!(&mut self.0, store => store.cache_clear())
+    }
+
+    fn cache_reset(&mut self) {
+        dispatch!(&mut self.0, store => store.cache_reset())
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        dispatch!(&mut self.0, store => store.cache_reset_metrics())
+    }
+
+    fn cache_size(&self) -> usize {
+        dispatch!(&self.0, store => store.cache_size())
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        dispatch!(&self.0, store => store.cache_hits())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        dispatch!(&self.0, store => store.cache_misses())
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        dispatch!(&self.0, store => store.cache_capacity())
+    }
+
+    fn cache_lifespan(&self) -> Option<Duration> {
+        dispatch!(&self.0, store => store.cache_lifespan())
+    }
+
+    fn cache_set_lifespan(&mut self, ttl: Duration) -> Option<Duration> {
+        dispatch!(&mut self.0, store => store.cache_set_lifespan(ttl))
+    }
+
+    fn cache_unset_lifespan(&mut self) -> Option<Duration> {
+        dispatch!(&mut self.0, store => store.cache_unset_lifespan())
+    }
+}
+
+impl<K: Hash + Eq + Clone> CloneCached<K, CachedResponse> for PolicyCache<K> {
+    fn cache_get_expired<Q>(&mut self, k: &Q) -> (Option<CachedResponse>, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match &mut self.0 {
+            Store::Ttl(store) => store.cache_get_expired(k),
+            // the responses are only evicted by the size limit, never expired
+            Store::Lru(store) => (store.cache_get(k).cloned(), false),
+            Store::Bytes(store) => store.cache_get_expired(k),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> CachedKeys<K> for PolicyCache<K> {
+    fn cache_keys(&self) -> Vec<K> {
+        dispatch!(&self.0, store => store.cache_keys())
+    }
+
+    fn cache_peek(&self, key: &K) -> Option<&CachedResponse> {
+        dispatch!(&self.0, store => store.cache_peek(key))
+    }
+}
+
+/// The builder of a [`CacheLayer`] with the store made for an [`EvictionPolicy`], see
+/// [`CacheLayer::builder`].
+pub struct CacheLayerBuilder<K = BasicKeyer> {
+    policy: EvictionPolicy,
+    keyer: K,
+    config: CacheConfig,
+}
+
+impl CacheLayer<PolicyCache<crate::BasicKey>, BasicKeyer> {
+    /// Start building a cache layer with the store bounded according to the eviction policy,
+    /// rather than a store of the [`cached`] crate picked by hand.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use axum_response_cache::{CacheConfig, CacheLayer, EvictionPolicy};
+    ///
+    /// // cache up to 64 MB of bodies for one minute
+    /// let layer = CacheLayer::builder(EvictionPolicy::TtlAndSize {
+    ///     ttl: Duration::from_secs(60),
+    ///     bytes: 64 * 1024 * 1024,
+    /// })
+    /// .config(CacheConfig {
+    ///     add_cache_status: true,
+    ///     ..CacheConfig::default()
+    /// })
+    /// .build();
+    /// ```
+    ///
+    /// The layer can be configured further with its own methods once it’s built.
+    pub fn builder(policy: EvictionPolicy) -> CacheLayerBuilder {
+        CacheLayerBuilder {
+            policy,
+            keyer: BasicKeyer,
+            config: CacheConfig::default(),
+        }
+    }
+}
+
+impl<K> CacheLayerBuilder<K>
+where
+    K: Keyer,
+    K::Key: Debug + Hash + Eq + Clone + Send + 'static,
+{
+    /// Use the keyer to obtain the keys of the cached responses, see
+    /// [`CacheLayer::with_cache_and_keyer`].
+    pub fn keyer<L: Keyer>(self, keyer: L) -> CacheLayerBuilder<L> {
+        CacheLayerBuilder {
+            policy: self.policy,
+            keyer,
+            config: self.config,
+        }
+    }
+
+    /// Apply the bundle of settings to the layer, see [`CacheLayer::from_config`].
+    pub fn config(self, config: CacheConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Build the layer.
+    pub fn build(self) -> CacheLayer<PolicyCache<K::Key>, K> {
+        CacheLayer::from_config(PolicyCache::new(self.policy), self.keyer, self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::Service;
+
+    use super::*;
+    use crate::axum::{
+        body::Body,
+        http::{Method, Request, StatusCode, Uri},
+        routing::get,
+        Router,
+    };
+
+    async fn request(router: &mut Router, path: &str) {
+        let response = router
+            .call(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[tokio::test]
+    async fn should_bound_cache_by_each_policy() {
+        let policies = [
+            (EvictionPolicy::Ttl(Duration::from_millis(50)), 4),
+            (EvictionPolicy::Lru(2), 2),
+            (EvictionPolicy::SizeBytes(30), 3),
+            (
+                EvictionPolicy::TtlAndSize {
+                    ttl: Duration::from_millis(50),
+                    bytes: 30,
+                },
+                3,
+            ),
+        ];
+        let paths = ["/a", "/b", "/c", "/d"];
+        for (policy, kept) in policies {
+            let layer = CacheLayer::builder(policy).build();
+            let mut router = Router::new()
+                .fallback(get(|| async { "ten bytes!" }))
+                .layer(layer.clone());
+            for path in paths {
+                request(&mut router, path).await;
+            }
+            let cached = |path| layer.peek(&(Method::GET, Uri::from_static(path))).is_some();
+            assert_eq!(
+                kept,
+                paths.into_iter().filter(|&path| cached(path)).count(),
+                "{policy:?} should’ve kept {kept} responses"
+            );
+            assert!(cached("/d"), "{policy:?} should’ve kept the last response");
+
+            // the expired entries are still peeked, until they’re evicted by a lookup
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            let hits = layer.stats().hits;
+            request(&mut router, "/d").await;
+            let expires = matches!(
+                policy,
+                EvictionPolicy::Ttl(_) | EvictionPolicy::TtlAndSize { .. }
+            );
+            assert_eq!(
+                expires,
+                layer.stats().hits == hits,
+                "{policy:?} should{} have expired the responses",
+                if expires { "" } else { "n’t" }
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Caching responses in Redis, see [`RedisStore`].
+
+use std::{
+    fmt::{self, Debug, Display},
+    future::Future,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use redis::{aio::ConnectionManager, AsyncCommands as _, RedisError};
+
+use crate::{AsyncCache, CachedResponse};
+
+/// The [`AsyncCache`] storing the responses in Redis, so that they can be shared by multiple
+/// instances of the service (eg. behind a load balancer).
+///
+/// The responses are stored under the `Debug` representation of their keys (or the one set with
+/// [`RedisStore::key_format`], prefixed with `axum-response-cache:` by default), and expire along with the Redis keys after the lifespan of
+/// the store (or earlier, according to their own freshness lifetime, see
+/// [`CacheLayer::honor_max_age`](crate::CacheLayer::honor_max_age)).
+///
+/// The store uses a [`ConnectionManager`], which multiplexes all the commands over a single
+/// connection and reconnects when it’s lost, so there’s no need for a connection pool.
+///
+/// ```rust,no_run
+/// # use axum_08 as axum;
+/// use std::time::Duration;
+/// use axum::{Router, routing::get};
+/// use axum_response_cache::{AsyncCacheLayer, RedisStore};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let connection = client.get_connection_manager().await.unwrap();
+/// let router: Router = Router::new().route(
+///     "/hello",
+///     get(|| async { "Hello, world!" })
+///         .layer(AsyncCacheLayer::with(RedisStore::new(connection, Duration::from_secs(60)))),
+/// );
+/// # }
+/// ```
+pub struct RedisStore<K> {
+    connection: ConnectionManager,
+    prefix: String,
+    lifespan: Duration,
+    key_format: Option<fn(&K) -> String>,
+    key: PhantomData<fn(&K)>,
+}
+
+impl<K> RedisStore<K> {
+    /// Create a new store keeping the responses for the given time.
+    pub fn new(connection: ConnectionManager, lifespan: Duration) -> Self {
+        Self {
+            connection,
+            prefix: String::from("axum-response-cache:"),
+            lifespan,
+            key_format: None,
+            key: PhantomData,
+        }
+    }
+
+    /// Change the prefix of the Redis keys, eg. to keep the responses of multiple layers apart.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Store the responses under the keys formatted with the given function rather than their
+    /// `Debug` representation, eg. with [`Keyer::key_to_string`](crate::Keyer::key_to_string):
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// use axum_response_cache::{BasicKeyer, Keyer, RedisStore};
+    ///
+    /// # fn store(connection: redis::aio::ConnectionManager) {
+    /// // the keys are like `axum-response-cache:GET /path?query`
+    /// let store = RedisStore::new(connection, Duration::from_secs(60))
+    ///     .key_format(|key| BasicKeyer.key_to_string(key));
+    /// # }
+    /// ```
+    pub fn key_format(mut self, format: fn(&K) -> String) -> Self {
+        self.key_format = Some(format);
+        self
+    }
+}
+
+impl<K: Debug> RedisStore<K> {
+    fn redis_key(&self, key: &K) -> String {
+        match self.key_format {
+            Some(format) => format!("{}{}", self.prefix, format(key)),
+            None => format!("{}{:?}", self.prefix, key),
+        }
+    }
+}
+
+impl<K: Debug + Send + Sync> AsyncCache<K> for RedisStore<K> {
+    type Error = RedisStoreError;
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send {
+        let mut connection = self.connection.clone();
+        let key = self.redis_key(key);
+        async move {
+            let data: Option<Vec<u8>> = connection.get(&key).await?;
+            data.map(|data| decode(&data)).transpose()
+        }
+    }
+
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let mut connection = self.connection.clone();
+        let key = self.redis_key(&key);
+        let lifespan = value.expires.map_or(self.lifespan, |expires| {
+            expires
+                .saturating_duration_since(Instant::now())
+                .min(self.lifespan)
+        });
+        let data = encode(&value);
+        async move {
+            let data = data?;
+            // the keys expire with the millisecond precision, and are stored for at least one
+            let millis = u64::try_from(lifespan.as_millis())
+                .unwrap_or(u64::MAX)
+                .max(1);
+            let () = connection.pset_ex(&key, data, millis).await?;
+            Ok(())
+        }
+    }
+
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let mut connection = self.connection.clone();
+        let key = self.redis_key(key);
+        async move {
+            let () = connection.del(&key).await?;
+            Ok(())
+        }
+    }
+}
+
+/// The error of the [`RedisStore`]’s operations.
+#[derive(Debug)]
+pub enum RedisStoreError {
+    /// The Redis command failed.
+    Redis(RedisError),
+    /// The response couldn’t be encoded.
+    Encode(rmp_serde::encode::Error),
+    /// The stored response couldn’t be decoded.
+    Decode(rmp_serde::decode::Error),
+}
+
+impl Display for RedisStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Redis(err) => write!(f, "Redis command failed: {err}"),
+            Self::Encode(err) => write!(f, "failed to encode the response: {err}"),
+            Self::Decode(err) => write!(f, "failed to decode the stored response: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RedisStoreError {}
+
+impl From<RedisError> for RedisStoreError {
+    fn from(err: RedisError) -> Self {
+        Self::Redis(err)
+    }
+}
+
+fn encode(value: &CachedResponse) -> Result<Vec<u8>, RedisStoreError> {
+    rmp_serde::to_vec(value).map_err(RedisStoreError::Encode)
+}
+
+fn decode(data: &[u8]) -> Result<CachedResponse, RedisStoreError> {
+    rmp_serde::from_slice(data).map_err(RedisStoreError::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicIsize, Ordering},
+        Arc,
+    };
+
+    use crate::axum::{
+        body::{self, Body},
+        extract::State,
+        http::Request,
+        response::IntoResponse as _,
+        routing::get,
+        Router,
+    };
+    use crate::AsyncCacheLayer;
+    use tower::Service;
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis server (at `REDIS_URL`)"]
+    async fn should_use_cached_value() {
+        let handler = |State(cnt): State<Arc<AtomicIsize>>| async move {
+            cnt.fetch_add(1, Ordering::AcqRel);
+            "Hello, world!".into_response()
+        };
+
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_owned());
+        let connection = redis::Client::open(url)
+            .unwrap()
+            .get_connection_manager()
+            .await
+            .unwrap();
+        let prefix = format!("axum-response-cache-test-{}:", std::process::id());
+        let store = RedisStore::new(connection, Duration::from_secs(60)).prefix(prefix);
+
+        let counter = Arc::new(AtomicIsize::new(0));
+        let mut router = Router::new()
+            .route("/", get(handler).layer(AsyncCacheLayer::with(store)))
+            .with_state(Arc::clone(&counter));
+
+        for _ in 0..10 {
+            let response = router
+                .call(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(
+                response.status().is_success(),
+                "handler should return success"
+            );
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!("Hello, world!", body, "cached body should be served");
+        }
+
+        assert_eq!(
+            1,
+            counter.load(Ordering::Acquire),
+            "handler should’ve been called only once"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! The serialization of the cached responses, for the stores outside of the process.
+
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_bytes::ByteBuf;
+
+use crate::axum::http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Version};
+use crate::{CachedResponse, Compression, Variant};
+
+/// The responses are serialized with their status, version, headers and body (the extensions are
+/// dropped). Their instants are serialized as the system time, so they’re meaningful to the other
+/// processes on the same host (or on the hosts with synchronized clocks).
+impl Serialize for CachedResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredResponse::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CachedResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::try_from(StoredResponse::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// The headers stored as the pairs of their names and values.
+type StoredHeaders = Vec<(String, ByteBuf)>;
+
+/// The serialized representation of a [`CachedResponse`].
+#[derive(Serialize, Deserialize)]
+struct StoredResponse {
+    status: u16,
+    version: u8,
+    headers: StoredHeaders,
+    body: ByteBuf,
+    timestamp: Option<SystemTime>,
+    expires: Option<SystemTime>,
+    variant: Option<(Vec<String>, StoredHeaders)>,
+    alternatives: Vec<StoredResponse>,
+    compression: Option<Compression>,
+    #[serde(default)]
+    encodings: Vec<(Compression, ByteBuf)>,
+}
+
+impl From<&CachedResponse> for StoredResponse {
+    fn from(value: &CachedResponse) -> Self {
+        Self {
+            status: value.parts.status.as_u16(),
+            version: match value.parts.version {
+                Version::HTTP_09 => 0,
+                Version::HTTP_10 => 1,
+                Version::HTTP_2 => 3,
+                Version::HTTP_3 => 4,
+                _ => 2,
+            },
+            headers: encode_headers(&value.parts.headers),
+            body: ByteBuf::from(value.body.to_vec()),
+            timestamp: Some(system_time(value.timestamp)),
+            expires: value.expires.map(system_time),
+            variant: value.variant.as_ref().map(|variant| {
+                (
+                    variant.names.iter().map(|name| name.to_string()).collect(),
+                    encode_headers(&variant.values),
+                )
+            }),
+            alternatives: value.alternatives.iter().map(Self::from).collect(),
+            compression: value.compression,
+            encodings: value
+                .encodings
+                .iter()
+                .map(|(coding, body)| (*coding, ByteBuf::from(body.to_vec())))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<StoredResponse> for CachedResponse {
+    type Error = &'static str;
+
+    fn try_from(stored: StoredResponse) -> Result<Self, Self::Error> {
+        let (mut parts, ()) = Response::new(()).into_parts();
+        parts.status = StatusCode::from_u16(stored.status).map_err(|_| "invalid status code")?;
+        parts.version = match stored.version {
+            0 => Version::HTTP_09,
+            1 => Version::HTTP_10,
+            2 => Version::HTTP_11,
+            3 => Version::HTTP_2,
+            4 => Version::HTTP_3,
+            _ => return Err("invalid HTTP version"),
+        };
+        parts.headers = decode_headers(stored.headers)?;
+        let variant = match stored.variant {
+            Some((names, values)) => Some(Arc::new(Variant {
+                names: names
+                    .into_iter()
+                    .map(|name| HeaderName::try_from(name).map_err(|_| "invalid header name"))
+                    .collect::<Result<_, _>>()?,
+                values: decode_headers(values)?,
+            })),
+            None => None,
+        };
+        Ok(Self {
+            parts: Arc::new(parts),
+            body: stored.body.into_vec().into(),
+            // the responses are stored with their timestamps, but they weren’t always recorded
+            timestamp: stored.timestamp.map_or_else(Instant::now, instant),
+            expires: stored.expires.map(instant),
+            variant,
+            alternatives: stored
+                .alternatives
+                .into_iter()
+                .map(Self::try_from)
+                .collect::<Result<_, _>>()?,
+            compression: stored.compression,
+            encodings: stored
+                .encodings
+                .into_iter()
+                .map(|(coding, body)| (coding, body.into_vec().into()))
+                .collect(),
+        })
+    }
+}
+
+fn encode_headers(headers: &HeaderMap) -> StoredHeaders {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), ByteBuf::from(value.as_bytes().to_vec())))
+        .collect()
+}
+
+fn decode_headers(headers: StoredHeaders) -> Result<HeaderMap, &'static str> {
+    let mut decoded = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        decoded.append(
+            HeaderName::try_from(name).map_err(|_| "invalid header name")?,
+            HeaderValue::from_bytes(&value).map_err(|_| "invalid header value")?,
+        );
+    }
+    Ok(decoded)
+}
+
+fn system_time(instant: Instant) -> SystemTime {
+    let (now, system_now) = (Instant::now(), SystemTime::now());
+    match instant.checked_duration_since(now) {
+        Some(ahead) => system_now + ahead,
+        None => system_now - now.duration_since(instant),
+    }
+}
+
+fn instant(time: SystemTime) -> Instant {
+    let (now, system_now) = (Instant::now(), SystemTime::now());
+    match time.duration_since(system_now) {
+        Ok(ahead) => now + ahead,
+        Err(err) => now.checked_sub(err.duration()).unwrap_or(now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axum::http::header;
+    use std::time::Duration;
+
+    #[test]
+    fn should_decode_encoded_responses() {
+        let (mut parts, ()) = Response::new(()).into_parts();
+        parts.status = StatusCode::NOT_FOUND;
+        parts
+            .headers
+            .append(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        parts
+            .headers
+            .append(header::VARY, HeaderValue::from_static("accept"));
+        let value = CachedResponse {
+            parts: Arc::new(parts),
+            body: "not found".into(),
+            timestamp: Instant::now() - Duration::from_secs(5),
+            expires: None,
+            variant: None,
+            alternatives: Arc::default(),
+            compression: None,
+            encodings: Arc::default(),
+        };
+
+        let decoded: CachedResponse =
+            rmp_serde::from_slice(&rmp_serde::to_vec(&value).unwrap()).unwrap();
+        assert_eq!(value.parts.status, decoded.parts.status);
+        assert_eq!(value.parts.headers, decoded.parts.headers);
+        assert_eq!(value.body, decoded.body);
+        assert!(
+            decoded.timestamp.elapsed() >= Duration::from_secs(5),
+            "timestamp should’ve been kept"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Caching responses in many separately locked stores, see [`ShardedCache`].
+
+use std::{
+    convert::Infallible,
+    future::{ready, Future},
+    hash::{BuildHasher, Hash, RandomState},
+    sync::{Mutex, MutexGuard},
+};
+
+use cached::Cached;
+
+use crate::{lock, AsyncCache, CachedResponse};
+
+/// The [`AsyncCache`] spreading the responses over many in-memory stores by the hashes of their
+/// keys, each behind its own lock, so that the concurrent requests for different keys rarely wait
+/// for each other (unlike with a single store locked by a [`CacheLayer`](crate::CacheLayer)).
+///
+/// Each shard is a separate store of the [`cached`] crate (or any other [`Cached`] one), so the
+/// limits of the stores apply to each shard on its own: eg. the shards of a
+/// [`SizedCache`](cached::SizedCache) of 100 responses keep up to 100 responses each, and evict
+/// them regardless of the other shards.
+///
+/// The gain over a single store depends on the number of the cores serving the requests in
+/// parallel; it can be measured with the `contention` benchmark (`cargo bench --bench
+/// contention`), comparing the hits on a single locked store and on the sharded ones.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use axum_response_cache::{AsyncCacheLayer, ShardedCache};
+/// use cached::TimedSizedCache;
+///
+/// // 16 shards of up to 1000 responses, each kept for one minute
+/// let layer = AsyncCacheLayer::with(ShardedCache::new(16, || {
+///     TimedSizedCache::with_size_and_lifespan(1000, Duration::from_secs(60))
+/// }));
+/// ```
+pub struct ShardedCache<C> {
+    shards: Box<[Mutex<C>]>,
+    hasher: RandomState,
+}
+
+impl<C> ShardedCache<C> {
+    /// Create a new store of the given number of shards (at least one), each made by the
+    /// function.
+    pub fn new(shards: usize, mut make: impl FnMut() -> C) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(make())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// The number of the shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Lock the shard of the given index, eg. to inspect its entries.
+    ///
+    /// # Panics
+    ///
+    /// If the index is out of the bounds of [`ShardedCache::shard_count`].
+    pub fn shard(&self, index: usize) -> MutexGuard<'_, C> {
+        lock(&self.shards[index])
+    }
+
+    /// Lock the shard of the key.
+    fn shard_of<K: Hash>(&self, key: &K) -> MutexGuard<'_, C> {
+        let index = self.hasher.hash_one(key) % self.shards.len() as u64;
+        lock(&self.shards[index as usize])
+    }
+}
+
+impl<K, C> AsyncCache<K> for ShardedCache<C>
+where
+    C: Cached<K, CachedResponse> + Send,
+    K: Hash + Eq + Sync,
+{
+    type Error = Infallible;
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> impl Future<Output = Result<Option<CachedResponse>, Self::Error>> + Send {
+        ready(Ok(self.shard_of(key).cache_get(key).cloned()))
+    }
+
+    fn set(
+        &self,
+        key: K,
+        value: CachedResponse,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.shard_of(&key).cache_set(key, value);
+        ready(Ok(()))
+    }
+
+    fn remove(&self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.shard_of(key).cache_remove(key);
+        ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use cached::TimedCache;
+    use tower::Service;
+
+    use super::*;
+    use crate::axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use crate::AsyncCacheLayer;
+
+    #[tokio::test]
+    async fn should_spread_responses_over_shards() {
+        let store = Arc::new(ShardedCache::new(4, || {
+            TimedCache::with_lifespan(Duration::from_secs(60))
+        }));
+        let mut router = Router::new()
+            .fallback(get(|| async { "sharded" }))
+            .layer(AsyncCacheLayer::with(Arc::clone(&store)));
+
+        for _ in 0..2 {
+            for id in 0..64 {
+                let response = router
+                    .call(Request::get(format!("/{id}")).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(StatusCode::OK, response.status());
+            }
+        }
+        let sizes: Vec<usize> = (0..store.shard_count())
+            .map(|index| store.shard(index).cache_size())
+            .collect();
+        assert_eq!(
+            64,
+            sizes.iter().sum::<usize>(),
+            "each response should be cached once"
+        );
+        assert!(
+            sizes.iter().all(|&size| size > 0),
+            "responses should be spread over all the shards: {sizes:?}"
+        );
+        let hits: u32 = (0..store.shard_count())
+            .map(|index| store.shard(index).cache_hits().unwrap_or_default() as u32)
+            .sum();
+        assert_eq!(64, hits, "second requests should’ve been hits");
+    }
+}
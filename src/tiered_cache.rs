@@ -0,0 +1,229 @@
+//! Caching responses in two stores at once, see [`TieredCache`].
+
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+};
+
+use tracing::debug;
+
+use crate::{AsyncCache, CachedResponse};
+
+/// The [`AsyncCache`] reading through a fast primary store (eg. an in-memory one, local to the
+/// process) to a slower secondary one (eg. the `RedisStore` shared by all the instances of the
+/// service), so that the responses cached by any instance are served by the others too, without
+/// reaching the shared store for every request.
+///
+/// The responses are looked up in the primary store first, and then in the secondary one, in
+/// which case they’re copied to the primary store before they’re served. The responses are
+/// written through both stores, the primary one first, so a failure of the secondary store
+/// leaves the response cached locally (the layer still logs it, and counts the response as not
+/// stored). The responses are removed from both stores.
+///
+/// Each store expires the responses on its own: a response copied from the secondary store to
+/// the primary one is kept for the whole lifespan of the primary store, counted from the copy, so
+/// it can be served for up to the sum of the lifespans after it was stored. The lifespan of the
+/// primary store is best kept short, as the responses refreshed by the other instances reach it
+/// only once they expire there. The freshness lifetimes of the responses themselves (see
+/// [`CacheLayer::honor_max_age`](crate::CacheLayer::honor_max_age)) are kept in both stores, and
+/// the age of the responses is counted from when they were first stored.
+///
+/// ```rust
+/// use std::{sync::Mutex, time::Duration};
+/// use axum_response_cache::{AsyncCacheLayer, TieredCache};
+/// use cached::TimedCache;
+///
+/// // eg. the `RedisStore` of the `redis` feature
+/// let shared = Mutex::new(TimedCache::with_lifespan(Duration::from_secs(3600)));
+/// let layer = AsyncCacheLayer::with(TieredCache::new(
+///     Mutex::new(TimedCache::with_lifespan(Duration::from_secs(10))),
+///     shared,
+/// ));
+/// ```
+pub struct TieredCache<P, S> {
+    primary: P,
+    secondary: S,
+}
+
+impl<P, S> TieredCache<P, S> {
+    /// Create a new store reading through the primary store to the secondary one.
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// The primary store, looked up first.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// The secondary store, looked up on the misses of the primary one.
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+impl<K, P, S> AsyncCache<K> for TieredCache<P, S>
+where
+    K: Debug + Clone + Send + Sync,
+    P: AsyncCache<K> + Sync,
+    S: AsyncCache<K> + Sync,
+    P::Error: Send,
+    S::Error: Send,
+{
+    type Error = TieredCacheError<P::Error, S::Error>;
+
+    async fn get(&self, key: &K) -> Result<Option<CachedResponse>, Self::Error> {
+        if let Some(value) = self
+            .primary
+            .get(key)
+            .await
+            .map_err(TieredCacheError::Primary)?
+        {
+            return Ok(Some(value));
+        }
+        let value = self
+            .secondary
+            .get(key)
+            .await
+            .map_err(TieredCacheError::Secondary)?;
+        if let Some(value) = &value {
+            debug!(
+                "Copying the response from the secondary store for key {:?}",
+                key
+            );
+            self.primary
+                .set(key.clone(), value.clone())
+                .await
+                .map_err(TieredCacheError::Primary)?;
+        }
+        Ok(value)
+    }
+
+    async fn set(&self, key: K, value: CachedResponse) -> Result<(), Self::Error> {
+        self.primary
+            .set(key.clone(), value.clone())
+            .await
+            .map_err(TieredCacheError::Primary)?;
+        self.secondary
+            .set(key, value)
+            .await
+            .map_err(TieredCacheError::Secondary)
+    }
+
+    async fn remove(&self, key: &K) -> Result<(), Self::Error> {
+        // the response is removed from the secondary store even if the primary one fails, so
+        // that it isn’t copied back
+        let primary = self.primary.remove(key).await;
+        self.secondary
+            .remove(key)
+            .await
+            .map_err(TieredCacheError::Secondary)?;
+        primary.map_err(TieredCacheError::Primary)
+    }
+}
+
+/// The error of a [`TieredCache`], failing in either of its stores.
+#[derive(Debug)]
+pub enum TieredCacheError<P, S> {
+    /// The primary store failed.
+    Primary(P),
+    /// The secondary store failed.
+    Secondary(S),
+}
+
+impl<P: Display, S: Display> Display for TieredCacheError<P, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary(err) => write!(f, "primary store failed: {err}"),
+            Self::Secondary(err) => write!(f, "secondary store failed: {err}"),
+        }
+    }
+}
+
+impl<P: Debug + Display, S: Debug + Display> Error for TieredCacheError<P, S> {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use cached::{Cached, TimedCache};
+    use tower::Service;
+
+    use super::*;
+    use crate::axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use crate::{AsyncCacheLayer, BasicKey};
+
+    type Store = Arc<Mutex<TimedCache<BasicKey, CachedResponse>>>;
+
+    /// The store shared by the layers of the tests, like the ones of the instances of a service.
+    struct SharedStore(Store);
+
+    impl AsyncCache<BasicKey> for SharedStore {
+        type Error = std::convert::Infallible;
+
+        async fn get(&self, key: &BasicKey) -> Result<Option<CachedResponse>, Self::Error> {
+            Ok(self.0.lock().unwrap().cache_get(key).cloned())
+        }
+
+        async fn set(&self, key: BasicKey, value: CachedResponse) -> Result<(), Self::Error> {
+            self.0.lock().unwrap().cache_set(key, value);
+            Ok(())
+        }
+
+        async fn remove(&self, key: &BasicKey) -> Result<(), Self::Error> {
+            self.0.lock().unwrap().cache_remove(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn should_read_through_to_secondary_store() {
+        let shared: Store = Arc::new(Mutex::new(TimedCache::with_lifespan(Duration::from_secs(
+            60,
+        ))));
+        let instance = |body: &'static str| {
+            let store = TieredCache::new(
+                Mutex::new(TimedCache::with_lifespan(Duration::from_secs(60))),
+                SharedStore(Arc::clone(&shared)),
+            );
+            Router::new().route(
+                "/",
+                get(move || async move { body }).layer(AsyncCacheLayer::with(store)),
+            )
+        };
+        let mut first = instance("first");
+        let mut second = instance("second");
+
+        let response = first
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            1,
+            shared.lock().unwrap().cache_size(),
+            "response should be shared"
+        );
+
+        // the other instance serves the shared response, and keeps it locally
+        for _ in 0..2 {
+            let response = second
+                .call(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = crate::axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!("first", body);
+            shared.lock().unwrap().cache_clear();
+        }
+    }
+}